@@ -1,20 +1,21 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Mutex;
-use strsim::levenshtein;
 use lazy_static::lazy_static;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 
 // --- Trie Data Structures ---
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 struct TrieNode {
     children: HashMap<char, TrieNode>,
     is_end_of_word: bool,
+    value: Option<String>,
+    frequency: usize,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 struct Trie {
     root: TrieNode,
 }
@@ -31,9 +32,22 @@ impl Trie {
         for c in word.chars() {
             current_node = current_node.children.entry(c).or_default();
         }
+        // Re-inserting an existing word bumps its frequency so that common
+        // terms can outrank rare ones at equal edit distance.
+        current_node.frequency += 1;
         current_node.is_end_of_word = true;
     }
 
+    fn insert_with_payload(&mut self, word: &str, payload: Option<String>) {
+        let mut current_node = &mut self.root;
+        for c in word.chars() {
+            current_node = current_node.children.entry(c).or_default();
+        }
+        current_node.frequency += 1;
+        current_node.is_end_of_word = true;
+        current_node.value = payload;
+    }
+
     fn delete(&mut self, word: &str) {
         fn _delete(node: &mut TrieNode, word: &str, index: usize) -> bool {
             if index == word.len() {
@@ -57,41 +71,137 @@ impl Trie {
     }
 
     fn search_fuzzy(&self, word: &str, max_distance: usize) -> Vec<FuzzyResult> {
+        self.search_fuzzy_inner(word, max_distance, false)
+    }
+
+    fn search_fuzzy_inner(&self, word: &str, max_distance: usize, transpositions: bool) -> Vec<FuzzyResult> {
+        let query: Vec<char> = word.chars().collect();
+        let m = query.len();
+        let mut results = Vec::new();
+        // The root DP row is the edit distance from the empty prefix to each
+        // prefix of the query: [0, 1, 2, ..., m].
+        let root_row: Vec<usize> = (0..=m).collect();
+        let mut prefix = String::new();
+        for (c, next_node) in &self.root.children {
+            self._search_recursive(
+                next_node, *c, None, &mut prefix, &query, &root_row, None, max_distance, transpositions,
+                &mut results,
+            );
+        }
+        results
+    }
+
+    fn search_fuzzy_ranked(&self, word: &str, max_distance: usize, limit: usize) -> Vec<FuzzyResult> {
+        let mut results = self.search_fuzzy(word, max_distance);
+        // Closest matches first, then most frequent, with the token breaking
+        // ties so the ordering is deterministic.
+        results.sort_by(|a, b| {
+            a.distance
+                .cmp(&b.distance)
+                .then(b.frequency.cmp(&a.frequency))
+                .then(a.token.cmp(&b.token))
+        });
+        results.truncate(limit);
+        results
+    }
+
+    fn autocomplete(&self, prefix: &str, limit: usize) -> Vec<String> {
+        // Walk down to the node that spells out `prefix`; bail early if the
+        // prefix is not present in the trie at all.
+        let mut node = &self.root;
+        for c in prefix.chars() {
+            match node.children.get(&c) {
+                Some(next_node) => node = next_node,
+                None => return Vec::new(),
+            }
+        }
+
         let mut results = Vec::new();
-        self._search_recursive(&self.root, "", word, max_distance, &mut results);
+        let mut buffer = prefix.to_string();
+        Self::_collect_words(node, &mut buffer, limit, &mut results);
         results
     }
 
+    fn _collect_words(node: &TrieNode, buffer: &mut String, limit: usize, results: &mut Vec<String>) {
+        if results.len() >= limit {
+            return;
+        }
+        if node.is_end_of_word {
+            results.push(buffer.clone());
+        }
+        // Push/pop the single shared buffer as we descend so we only allocate
+        // one owned string per emitted word, not per visited node.
+        for (c, child) in &node.children {
+            buffer.push(*c);
+            Self::_collect_words(child, buffer, limit, results);
+            buffer.pop();
+            if results.len() >= limit {
+                return;
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn _search_recursive(
         &self,
         node: &TrieNode,
-        prefix: &str,
-        word: &str,
+        c: char,
+        parent_c: Option<char>,
+        prefix: &mut String,
+        query: &[char],
+        prev_row: &[usize],
+        prev_prev_row: Option<&[usize]>,
         max_distance: usize,
+        transpositions: bool,
         results: &mut Vec<FuzzyResult>,
     ) {
-        if !prefix.is_empty() {
-             let distance = levenshtein(prefix, word);
-            if distance <= max_distance {
-                if node.is_end_of_word {
-                    results.push(FuzzyResult {
-                        token: prefix.to_string(),
-                        distance,
-                    });
+        let m = query.len();
+        // Incrementally derive this node's DP row from the parent's row by
+        // adding a single trie character `c`, so each node costs O(m) rather
+        // than re-running the full O(prefix*word) recurrence.
+        let mut current_row = Vec::with_capacity(m + 1);
+        current_row.push(prev_row[0] + 1);
+        for j in 1..=m {
+            let cost = if query[j - 1] == c { 0 } else { 1 };
+            let mut value = (current_row[j - 1] + 1)
+                .min(prev_row[j] + 1)
+                .min(prev_row[j - 1] + cost);
+            // Optimal String Alignment transposition: swapping this node's
+            // character with its parent's lines them up with the preceding two
+            // query characters, so the swap costs a single edit.
+            if transpositions && j >= 2 {
+                if let (Some(pp), Some(pc)) = (prev_prev_row, parent_c) {
+                    if c == query[j - 2] && pc == query[j - 1] {
+                        value = value.min(pp[j - 2] + 1);
+                    }
                 }
             }
-             // Pruning the search space
-            let min_possible_dist = prefix.chars().count().abs_diff(word.chars().count());
-            if min_possible_dist > max_distance && distance > max_distance {
-                 return;
-            }
+            current_row.push(value);
         }
 
+        prefix.push(c);
 
-        for (char, next_node) in &node.children {
-            let new_prefix = format!("{}{}", prefix, char);
-            self._search_recursive(next_node, &new_prefix, word, max_distance, results);
+        if node.is_end_of_word && current_row[m] <= max_distance {
+            results.push(FuzzyResult {
+                token: prefix.clone(),
+                distance: current_row[m],
+                payload: node.value.clone(),
+                frequency: node.frequency,
+            });
         }
+
+        // If every entry in the row already exceeds the budget, no descendant
+        // can recover, so the whole subtree is pruned.
+        if current_row.iter().min().copied().unwrap_or(0) <= max_distance {
+            for (next_c, next_node) in &node.children {
+                self._search_recursive(
+                    next_node, *next_c, Some(c), prefix, query, &current_row, Some(prev_row), max_distance,
+                    transpositions, results,
+                );
+            }
+        }
+
+        prefix.pop();
     }
 }
 
@@ -101,56 +211,209 @@ impl Trie {
 struct FuzzyResult {
     token: String,
     distance: usize,
+    payload: Option<String>,
+    frequency: usize,
 }
 
-// --- Global Static Trie Instance ---
+// --- Global Trie Registry ---
+
+/// Holds every live trie keyed by an opaque handle, so a single host process
+/// can keep multiple independent indexes (e.g. products and users) side by
+/// side instead of sharing one global dictionary.
+struct Registry {
+    tries: HashMap<u64, Trie>,
+    next_handle: u64,
+}
 
 lazy_static! {
-    static ref TRIE: Mutex<Trie> = Mutex::new(Trie::new());
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+        tries: HashMap::new(),
+        next_handle: 1,
+    });
 }
 
 // --- Exposed FFI Functions ---
 
-/// Initializes or resets the global Trie.
+/// Creates a new, empty trie and returns its handle. The handle must be passed
+/// to every subsequent operation and released with `destroy_trie`.
 #[no_mangle]
-pub extern "C" fn init_trie() {
-    *TRIE.lock().unwrap() = Trie::new();
+pub extern "C" fn create_trie() -> u64 {
+    let mut registry = REGISTRY.lock().unwrap();
+    let handle = registry.next_handle;
+    registry.next_handle += 1;
+    registry.tries.insert(handle, Trie::new());
+    handle
 }
 
-/// Inserts a word into the Trie. Expects a null-terminated C string.
+/// Destroys the trie behind `handle`, freeing its memory. A no-op if the
+/// handle is unknown.
 #[no_mangle]
-pub extern "C" fn insert_word(word_c: *const c_char) {
+pub extern "C" fn destroy_trie(handle: u64) {
+    REGISTRY.lock().unwrap().tries.remove(&handle);
+}
+
+/// Inserts a word into the trie `handle`. Expects a null-terminated C string.
+#[no_mangle]
+pub extern "C" fn insert_word(handle: u64, word_c: *const c_char) {
     let word = unsafe { CStr::from_ptr(word_c).to_str().unwrap_or("") };
     if !word.is_empty() {
-        TRIE.lock().unwrap().insert(word);
+        if let Some(trie) = REGISTRY.lock().unwrap().tries.get_mut(&handle) {
+            trie.insert(word);
+        }
     }
 }
 
-/// Deletes a word from the Trie. Expects a null-terminated C string.
+/// Inserts a word along with an opaque payload (e.g. a document id or JSON
+/// blob) that is returned with every fuzzy match on that word. Both strings
+/// are null-terminated C strings.
 #[no_mangle]
-pub extern "C" fn delete_word(word_c: *const c_char) {
+pub extern "C" fn insert_word_with_payload(handle: u64, word_c: *const c_char, payload_c: *const c_char) {
     let word = unsafe { CStr::from_ptr(word_c).to_str().unwrap_or("") };
+    let payload = unsafe { CStr::from_ptr(payload_c).to_str().unwrap_or("") };
     if !word.is_empty() {
-        TRIE.lock().unwrap().delete(word);
+        if let Some(trie) = REGISTRY.lock().unwrap().tries.get_mut(&handle) {
+            trie.insert_with_payload(word, Some(payload.to_string()));
+        }
     }
 }
 
-/// Performs a fuzzy search. Returns results as a JSON string.
+/// Deletes a word from the trie `handle`. Expects a null-terminated C string.
+#[no_mangle]
+pub extern "C" fn delete_word(handle: u64, word_c: *const c_char) {
+    let word = unsafe { CStr::from_ptr(word_c).to_str().unwrap_or("") };
+    if !word.is_empty() {
+        if let Some(trie) = REGISTRY.lock().unwrap().tries.get_mut(&handle) {
+            trie.delete(word);
+        }
+    }
+}
+
+/// Performs a fuzzy search against the trie `handle`. Returns results as a
+/// JSON string. The caller is responsible for freeing the returned string.
+#[no_mangle]
+pub extern "C" fn search_fuzzy(handle: u64, word_c: *const c_char, max_distance: usize) -> *mut c_char {
+    let word = unsafe { CStr::from_ptr(word_c).to_str().unwrap_or("") };
+    if word.is_empty() {
+        let empty_json = CString::new("[]").unwrap();
+        return empty_json.into_raw();
+    }
+
+    let registry = REGISTRY.lock().unwrap();
+    let results = registry
+        .tries
+        .get(&handle)
+        .map(|trie| trie.search_fuzzy(word, max_distance))
+        .unwrap_or_default();
+    let json_string = serde_json::to_string(&results).unwrap_or_else(|_| "[]".to_string());
+
+    CString::new(json_string).unwrap().into_raw()
+}
+
+/// Performs a fuzzy search allowing adjacent-character transpositions
+/// (Optimal String Alignment), so typos like "teh" match "the" within a
+/// single edit. Returns results as a JSON string; the caller frees it.
+#[no_mangle]
+pub extern "C" fn search_fuzzy_damerau(handle: u64, word_c: *const c_char, max_distance: usize) -> *mut c_char {
+    let word = unsafe { CStr::from_ptr(word_c).to_str().unwrap_or("") };
+    if word.is_empty() {
+        let empty_json = CString::new("[]").unwrap();
+        return empty_json.into_raw();
+    }
+
+    let registry = REGISTRY.lock().unwrap();
+    let results = registry
+        .tries
+        .get(&handle)
+        .map(|trie| trie.search_fuzzy_inner(word, max_distance, true))
+        .unwrap_or_default();
+    let json_string = serde_json::to_string(&results).unwrap_or_else(|_| "[]".to_string());
+
+    CString::new(json_string).unwrap().into_raw()
+}
+
+/// Performs a fuzzy search and returns the top `limit` matches ranked by
+/// ascending edit distance then descending frequency, as a JSON string.
 /// The caller is responsible for freeing the memory of the returned string.
 #[no_mangle]
-pub extern "C" fn search_fuzzy(word_c: *const c_char, max_distance: usize) -> *mut c_char {
+pub extern "C" fn search_fuzzy_ranked(
+    handle: u64,
+    word_c: *const c_char,
+    max_distance: usize,
+    limit: usize,
+) -> *mut c_char {
     let word = unsafe { CStr::from_ptr(word_c).to_str().unwrap_or("") };
     if word.is_empty() {
         let empty_json = CString::new("[]").unwrap();
         return empty_json.into_raw();
     }
 
-    let results = TRIE.lock().unwrap().search_fuzzy(word, max_distance);
+    let registry = REGISTRY.lock().unwrap();
+    let results = registry
+        .tries
+        .get(&handle)
+        .map(|trie| trie.search_fuzzy_ranked(word, max_distance, limit))
+        .unwrap_or_default();
     let json_string = serde_json::to_string(&results).unwrap_or_else(|_| "[]".to_string());
 
     CString::new(json_string).unwrap().into_raw()
 }
 
+/// Returns up to `limit` words that share the given prefix, as a JSON array.
+/// The caller is responsible for freeing the memory of the returned string.
+#[no_mangle]
+pub extern "C" fn autocomplete(handle: u64, prefix_c: *const c_char, limit: usize) -> *mut c_char {
+    let prefix = unsafe { CStr::from_ptr(prefix_c).to_str().unwrap_or("") };
+    let registry = REGISTRY.lock().unwrap();
+    let results = registry
+        .tries
+        .get(&handle)
+        .map(|trie| trie.autocomplete(prefix, limit))
+        .unwrap_or_default();
+    let json_string = serde_json::to_string(&results).unwrap_or_else(|_| "[]".to_string());
+
+    CString::new(json_string).unwrap().into_raw()
+}
+
+/// Serializes the trie `handle` to a compact binary snapshot at `path_c`.
+/// Returns `true` on success so a large index can be built once and reloaded
+/// cheaply on later runs.
+#[no_mangle]
+pub extern "C" fn save_trie(handle: u64, path_c: *const c_char) -> bool {
+    let path = unsafe { CStr::from_ptr(path_c).to_str().unwrap_or("") };
+    if path.is_empty() {
+        return false;
+    }
+    let registry = REGISTRY.lock().unwrap();
+    let Some(trie) = registry.tries.get(&handle) else {
+        return false;
+    };
+    match bincode::serialize(trie) {
+        Ok(bytes) => std::fs::write(path, bytes).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Loads a binary snapshot written by `save_trie` into a fresh trie and
+/// returns its handle, or `0` if the file cannot be read or decoded.
+#[no_mangle]
+pub extern "C" fn load_trie(path_c: *const c_char) -> u64 {
+    let path = unsafe { CStr::from_ptr(path_c).to_str().unwrap_or("") };
+    if path.is_empty() {
+        return 0;
+    }
+    let Ok(bytes) = std::fs::read(path) else {
+        return 0;
+    };
+    let Ok(trie) = bincode::deserialize::<Trie>(&bytes) else {
+        return 0;
+    };
+    let mut registry = REGISTRY.lock().unwrap();
+    let handle = registry.next_handle;
+    registry.next_handle += 1;
+    registry.tries.insert(handle, trie);
+    handle
+}
+
 /// Frees the memory of a C string that was allocated by Rust.
 #[no_mangle]
 pub extern "C" fn free_string(s: *mut c_char) {